@@ -14,7 +14,6 @@
  * limitations under the License.
  */
 
-use std::cell::RefCell;
 use std::io::Cursor;
 
 use anyhow::Result;
@@ -75,6 +74,39 @@ pub struct BinaryProtocolSerializer<B> {
 
 pub struct BinaryProtocolDeserializer<B> {
     buffer: B,
+    limits: DeserializeLimits,
+    remaining_bytes: Option<usize>,
+    // Saved `try_skip_fast` progress from a prior `NeedMore`, so the next
+    // call resumes instead of re-skipping from the top.
+    resume_stack: Option<Vec<SkipData>>,
+}
+
+/// Opt-in limits bounding how much a `BinaryProtocolDeserializer` will trust
+/// lengths read off the wire, so that a hostile or corrupt 4-byte container
+/// length can't drive a multi-gigabyte allocation. Modeled on bincode's
+/// `Bounded` limit config.
+///
+/// Defaults (`DeserializeLimits::default()`) impose no byte or element
+/// budget and use the same recursion depth as unconfigured deserializers.
+#[derive(Debug, Clone, Copy)]
+pub struct DeserializeLimits {
+    /// Total bytes this deserializer is allowed to charge against strings,
+    /// binary blobs, and container elements before giving up.
+    pub max_total_bytes: Option<usize>,
+    /// Maximum number of elements any single map/list/set header may claim.
+    pub max_container_len: Option<usize>,
+    /// Maximum nested struct/container depth honored by `skip`/`try_skip`.
+    pub recursion_depth: u32,
+}
+
+impl Default for DeserializeLimits {
+    fn default() -> Self {
+        DeserializeLimits {
+            max_total_bytes: None,
+            max_container_len: None,
+            recursion_depth: DEFAULT_RECURSION_DEPTH,
+        }
+    }
 }
 
 impl<F> Protocol for BinaryProtocol<F>
@@ -131,13 +163,51 @@ impl<B: BufMutExt> BinaryProtocolSerializer<B> {
 impl<B: BufExt> BinaryProtocolDeserializer<B> {
     #[inline]
     pub fn new(buffer: B) -> Self {
-        BinaryProtocolDeserializer { buffer }
+        Self::with_limits(buffer, DeserializeLimits::default())
+    }
+
+    /// Build a deserializer that enforces `limits` on container sizes,
+    /// total allocated bytes, and skip recursion depth, rejecting untrusted
+    /// input with `ProtocolError` instead of acting on wire-supplied sizes
+    /// unconditionally.
+    #[inline]
+    pub fn with_limits(buffer: B, limits: DeserializeLimits) -> Self {
+        let remaining_bytes = limits.max_total_bytes;
+        BinaryProtocolDeserializer {
+            buffer,
+            limits,
+            remaining_bytes,
+            resume_stack: None,
+        }
     }
 
     #[inline]
     pub fn into_inner(self) -> B {
         self.buffer
     }
+
+    /// Deduct `len` bytes from the configured total-bytes budget, if any,
+    /// failing closed when the budget is exhausted.
+    #[inline]
+    fn charge_bytes(&mut self, len: usize) -> Result<()> {
+        if let Some(remaining) = self.remaining_bytes.as_mut() {
+            ensure_err!(*remaining >= len, ProtocolError::SizeLimitExceeded);
+            *remaining -= len;
+        }
+        Ok(())
+    }
+
+    /// Validate a wire-supplied container element count against the
+    /// configured limit and charge its rough memory cost against the
+    /// total-bytes budget.
+    #[inline]
+    fn charge_container_len(&mut self, len: usize, per_elem_size: usize) -> Result<()> {
+        if let Some(max_len) = self.limits.max_container_len {
+            ensure_err!(len <= max_len, ProtocolError::SizeLimitExceeded);
+        }
+        self.charge_bytes(len.saturating_mul(per_elem_size))
+    }
+
     #[inline]
     fn peek_bytes(&self, len: usize) -> Option<&[u8]> {
         if self.buffer.chunk().len() >= len {
@@ -153,167 +223,206 @@ impl<B: BufExt> BinaryProtocolDeserializer<B> {
         Ok(self.buffer.get_u32())
     }
 
-    fn skip_fast(&mut self, field_type: TType, stack: &mut [SkipData]) -> Result<()> {
-        const TYPE_FIXED_SIZE: [usize; 20] = [
-            0, // TType::Stop
-            0, // TType::Void
-            1, // TType::Bool
-            1, // TType::Byte
-            8, // TType::Double
-            0, // NAN
-            2, // TType::I16
-            0, // NAN
-            4, // TType::I32
-            0, // NAN
-            8, // TType::I64
-            0, // TType::String
-            0, // TType::Struct
-            0, // TType::Map
-            0, // TType::Set
-            0, // TType::List
-            0, // TType::UTF8
-            0, // TType::UTF16
-            0, // TType::Stream
-            4, // TType::Float
-        ];
-        let mut stack_len: usize = 0;
-        macro_rules! pop {
-            () => {
-                match stack_len.checked_sub(1) {
-                    Some(last) => {
-                        stack_len = last;
-                        stack[last]
-                    }
-                    None => break,
-                }
-            };
+}
+
+#[derive(Debug, Copy, Clone)]
+enum SkipData {
+    Collection(u32, [TType; 2]),
+    Next(TType),
+}
+
+const TYPE_FIXED_SIZE: [usize; 20] = [
+    0, // TType::Stop
+    0, // TType::Void
+    1, // TType::Bool
+    1, // TType::Byte
+    8, // TType::Double
+    0, // NAN
+    2, // TType::I16
+    0, // NAN
+    4, // TType::I32
+    0, // NAN
+    8, // TType::I64
+    0, // TType::String
+    0, // TType::Struct
+    0, // TType::Map
+    0, // TType::Set
+    0, // TType::List
+    0, // TType::UTF8
+    0, // TType::UTF16
+    0, // TType::Stream
+    4, // TType::Float
+];
+
+/// Outcome of a resumable read: either the fully decoded value, or a signal
+/// that the underlying buffer ran out before this call could make progress.
+///
+/// [`BinaryProtocolDeserializer::try_skip`] produces this, and
+/// `ProtocolReader::skip` (the trait method generated code calls to skip a
+/// field it doesn't recognize) is implemented on top of it: on `NeedMore`,
+/// `skip` still surfaces the fatal `ProtocolError::EOF` (its signature can't
+/// express a retry), but the in-progress skip stack has already been saved
+/// to `resume_stack`, so a streaming caller that appends more bytes to the
+/// same buffer and calls `skip` again resumes from that point instead of
+/// re-skipping the field from the top. The `read_*` methods used to decode
+/// fields generated code *does* recognize (`read_i32`, `read_string`,
+/// `read_struct_begin`, `read_map_begin_unchecked`, ...), and the top-level
+/// [`deserialize`] entry point, are not resumable: a short read there is
+/// still a hard failure with no saved progress.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeserializeOutcome<T> {
+    Done(T),
+    /// The caller should append at least `bytes_hint` more bytes to the
+    /// buffer and retry the exact same call; cursor state (including
+    /// partial skip progress) has already been preserved internally.
+    NeedMore { bytes_hint: usize },
+}
+
+impl<B: BufExt> BinaryProtocolDeserializer<B> {
+    /// Incrementally skip a value of `field_type`, for streaming transports
+    /// that can't guarantee the whole message is buffered up front.
+    ///
+    /// This is what `ProtocolReader::skip` calls internally. Unlike `skip`,
+    /// which can only surface a short read as the fatal
+    /// `ProtocolError::EOF`, calling this directly returns `NeedMore`
+    /// instead and keeps the in-progress skip stack (including any
+    /// partially-unwound nested struct/container state) so that calling
+    /// `try_skip` again after more bytes have been appended to the buffer
+    /// resumes exactly where the previous call left off, rather than
+    /// re-skipping from the top.
+    ///
+    /// Every read this performs checks `can_advance` for the full size of
+    /// what it's about to consume (a struct field header, a collection
+    /// header, a length-prefixed string) before consuming any of it, so a
+    /// short read never desyncs the saved resume point.
+    pub fn try_skip(&mut self, field_type: TType) -> Result<DeserializeOutcome<()>> {
+        let mut stack = self
+            .resume_stack
+            .take()
+            .unwrap_or_else(|| vec![SkipData::Next(field_type)]);
+        match self.try_skip_fast(&mut stack)? {
+            None => Ok(DeserializeOutcome::Done(())),
+            Some(bytes_hint) => {
+                self.resume_stack = Some(stack);
+                Ok(DeserializeOutcome::NeedMore {
+                    bytes_hint: bytes_hint.max(1),
+                })
+            }
         }
-        macro_rules! push {
-            ($elem: expr) => {
-                if stack_len >= stack.len() {
-                    bail_err!(ProtocolError::SkipDepthExceeded);
-                }
-                stack[stack_len] = $elem;
-                stack_len += 1;
-            };
+    }
+
+    /// Returns `Ok(None)` once `stack` is fully unwound, or
+    /// `Ok(Some(bytes_hint))` with the failing frame pushed back onto
+    /// `stack` so the caller can retry unchanged.
+    fn try_skip_fast(&mut self, stack: &mut Vec<SkipData>) -> Result<Option<usize>> {
+        macro_rules! suspend {
+            ($frame:expr, $needed:expr) => {{
+                stack.push($frame);
+                return Ok(Some($needed.saturating_sub(self.buffer.remaining())));
+            }};
         }
-        macro_rules! advance {
-            ($n: expr) => {
-                ensure_err!(self.can_advance($n), ProtocolError::EOF);
-                self.buffer.advance($n);
+        macro_rules! push_checked {
+            ($elem:expr) => {
+                ensure_err!(
+                    stack.len() < self.limits.recursion_depth as usize,
+                    ProtocolError::SkipDepthExceeded
+                );
+                stack.push($elem);
             };
         }
 
-        let mut current = SkipData::Next(field_type);
         loop {
+            let current = match stack.pop() {
+                Some(current) => current,
+                None => return Ok(None),
+            };
             match current {
                 SkipData::Next(ttype) => {
                     let to_skip = *TYPE_FIXED_SIZE.get(ttype as usize).expect("unexpect ttype");
                     if to_skip > 0 {
-                        advance!(to_skip);
-                        current = pop!();
+                        if !self.can_advance(to_skip) {
+                            suspend!(SkipData::Next(ttype), to_skip);
+                        }
+                        self.buffer.advance(to_skip);
                         continue;
                     }
                     match ttype {
                         TType::Struct => {
-                            let (_, field_type, _) = self.read_field_begin(|_| (), &[])?;
-                            let size = *TYPE_FIXED_SIZE
-                                .get(field_type as usize)
-                                .expect("unexpect ttype");
-                            if size != 0 {
-                                advance!(size);
-                                continue;
+                            if !self.can_advance(1) {
+                                suspend!(SkipData::Next(TType::Struct), 1);
                             }
-
-                            match field_type {
-                                TType::Stop => {
-                                    current = pop!();
-                                }
-                                _ => {
-                                    push!(current);
-                                    current = SkipData::Next(field_type);
-                                }
+                            let type_byte = self.peek_bytes(1).expect("checked above")[0];
+                            let header_len = if TType::try_from(type_byte as i8)? == TType::Stop {
+                                1
+                            } else {
+                                3
+                            };
+                            if !self.can_advance(header_len) {
+                                suspend!(SkipData::Next(TType::Struct), header_len);
+                            }
+                            let (_, field_type, _) = self.read_field_begin(|_| (), &[])?;
+                            if field_type != TType::Stop {
+                                push_checked!(SkipData::Next(TType::Struct));
+                                push_checked!(SkipData::Next(field_type));
                             }
                         }
                         TType::List | TType::Set => {
+                            if !self.can_advance(5) {
+                                suspend!(SkipData::Next(ttype), 5);
+                            }
                             let elem_type = TType::try_from(self.read_byte()?)?;
-                            let elem_len = self
+                            let elem_len: u32 = self
                                 .read_i32()?
                                 .try_into()
                                 .map_err(|_| ProtocolError::InvalidDataLength)?;
-                            let per_elem_size = *TYPE_FIXED_SIZE
-                                .get(elem_type as usize)
-                                .expect("unexpect ttype");
-                            if per_elem_size != 0 {
-                                let skip = (elem_len as usize)
-                                    .checked_mul(per_elem_size)
-                                    .ok_or(ProtocolError::InvalidDataLength)?;
-                                advance!(skip);
-                                current = pop!();
-                            } else {
-                                current = SkipData::Collection(elem_len, [elem_type, elem_type]);
-                            }
+                            push_checked!(SkipData::Collection(elem_len, [elem_type, elem_type]));
                         }
                         TType::Map => {
+                            if !self.can_advance(6) {
+                                suspend!(SkipData::Next(TType::Map), 6);
+                            }
                             let key_type = TType::try_from(self.read_byte()?)?;
                             let val_type = TType::try_from(self.read_byte()?)?;
                             let elem_len: u32 = self
                                 .read_i32()?
                                 .try_into()
                                 .map_err(|_| ProtocolError::InvalidDataLength)?;
-
-                            let per_key_size = *TYPE_FIXED_SIZE
-                                .get(key_type as usize)
-                                .expect("unexpect ttype");
-                            let per_val_size = *TYPE_FIXED_SIZE
-                                .get(val_type as usize)
-                                .expect("unexpect ttype");
-
-                            if per_key_size != 0 && per_val_size != 0 {
-                                let skip = (elem_len as usize)
-                                    .checked_mul(per_key_size + per_val_size)
-                                    .ok_or(ProtocolError::InvalidDataLength)?;
-                                advance!(skip);
-                                current = pop!();
-                            } else {
-                                current = SkipData::Collection(elem_len * 2, [key_type, val_type]);
-                            }
+                            push_checked!(SkipData::Collection(
+                                elem_len * 2,
+                                [key_type, val_type]
+                            ));
                         }
                         TType::String | TType::UTF8 | TType::UTF16 => {
+                            if !self.can_advance(4) {
+                                suspend!(SkipData::Next(ttype), 4);
+                            }
+                            let len_bytes: [u8; 4] =
+                                self.peek_bytes(4).expect("checked above").try_into().unwrap();
+                            let len = i32::from_be_bytes(len_bytes);
+                            ensure_err!(len >= 0, ProtocolError::InvalidDataLength);
+                            let total = 4 + len as usize;
+                            if !self.can_advance(total) {
+                                suspend!(SkipData::Next(ttype), total);
+                            }
                             self.read_binary::<Discard>()?;
-                            current = pop!();
                         }
-                        TType::Void => {
-                            current = pop!();
-                        }
-                        TType::Stop => bail_err!(ProtocolError::UnexpectedStopInSkip),
+                        TType::Void | TType::Stop => {}
                         TType::Stream => bail_err!(ProtocolError::StreamUnsupported),
-                        _ => {
-                            unreachable!("unexpect ttype: {:?}", ttype)
-                        }
+                        _ => unreachable!("unexpect ttype: {:?}", ttype),
                     }
                 }
                 SkipData::Collection(len, ttypes) => {
                     if len == 0 {
-                        current = pop!();
                         continue;
                     }
-                    current = SkipData::Next(ttypes[(len & 1) as usize]);
-                    push!(SkipData::Collection(len - 1, ttypes));
+                    push_checked!(SkipData::Collection(len - 1, ttypes));
+                    push_checked!(SkipData::Next(ttypes[(len & 1) as usize]));
                 }
             }
         }
-
-        Ok(())
     }
 }
 
-#[derive(Debug, Copy, Clone)]
-enum SkipData {
-    Collection(u32, [TType; 2]),
-    Next(TType),
-}
-
 impl<B: BufMutExt> ProtocolWriter for BinaryProtocolSerializer<B> {
     type Final = B::Final;
     #[inline]
@@ -429,6 +538,83 @@ impl<B: BufMutExt> ProtocolWriter for BinaryProtocolSerializer<B> {
     }
 }
 
+/// Thrift has no wire type wider than 64 bits, so 128/256-bit integers ride
+/// inside a length-prefixed `TType::String`/binary field, matching the
+/// ethnum `bytes::be`/`bytes::le` scheme. Generated code for a field wider
+/// than `i64` should call one of these instead of hand-rolling the byte
+/// layout.
+impl<B: BufMutExt> BinaryProtocolSerializer<B> {
+    /// Big-endian fixed-width encoding, mirroring ethnum's `bytes::be`.
+    #[inline]
+    pub fn write_i128(&mut self, value: i128) {
+        self.write_binary(&value.to_be_bytes())
+    }
+    #[inline]
+    pub fn write_u128(&mut self, value: u128) {
+        self.write_binary(&value.to_be_bytes())
+    }
+
+    /// Little-endian fixed-width encoding, mirroring ethnum's `bytes::le`.
+    #[inline]
+    pub fn write_i128_le(&mut self, value: i128) {
+        self.write_binary(&value.to_le_bytes())
+    }
+    #[inline]
+    pub fn write_u128_le(&mut self, value: u128) {
+        self.write_binary(&value.to_le_bytes())
+    }
+
+    /// Strips leading bytes that are redundant with the sign, for values
+    /// that are usually small; `read_i128_compressed` sign-extends back to
+    /// full width.
+    #[inline]
+    pub fn write_i128_compressed(&mut self, value: i128) {
+        let bytes = value.to_be_bytes();
+        self.write_binary(compress_be_signed(&bytes))
+    }
+    /// Strips leading zero bytes; `read_u128_compressed` zero-extends back
+    /// to full width.
+    #[inline]
+    pub fn write_u128_compressed(&mut self, value: u128) {
+        let bytes = value.to_be_bytes();
+        self.write_binary(compress_be_unsigned(&bytes))
+    }
+
+    #[cfg(feature = "int256")]
+    #[inline]
+    pub fn write_u256(&mut self, value: ethnum::U256) {
+        self.write_binary(&value.to_be_bytes())
+    }
+    #[cfg(feature = "int256")]
+    #[inline]
+    pub fn write_u256_le(&mut self, value: ethnum::U256) {
+        self.write_binary(&value.to_le_bytes())
+    }
+}
+
+/// Drop leading bytes that only repeat the sign bit of the following byte,
+/// leaving at least one byte so the value (and its sign) can be recovered.
+fn compress_be_signed(bytes: &[u8]) -> &[u8] {
+    let sign = if bytes[0] & 0x80 != 0 { 0xffu8 } else { 0x00u8 };
+    let mut start = 0;
+    while start + 1 < bytes.len()
+        && bytes[start] == sign
+        && (bytes[start + 1] & 0x80 != 0) == (sign == 0xff)
+    {
+        start += 1;
+    }
+    &bytes[start..]
+}
+
+/// Drop leading zero bytes, leaving at least one byte.
+fn compress_be_unsigned(bytes: &[u8]) -> &[u8] {
+    let mut start = 0;
+    while start + 1 < bytes.len() && bytes[start] == 0 {
+        start += 1;
+    }
+    &bytes[start..]
+}
+
 impl<B: BufExt> ProtocolReader for BinaryProtocolDeserializer<B> {
     #[inline]
     fn read_message_begin<F, T>(&mut self, msgfn: F) -> Result<(T, MessageType, u32)>
@@ -501,7 +687,13 @@ impl<B: BufExt> ProtocolReader for BinaryProtocolDeserializer<B> {
 
         let size = self.read_i32()?;
         ensure_err!(size >= 0, ProtocolError::InvalidDataLength);
-        Ok((k_type, v_type, Some(size as usize)))
+        let size = size as usize;
+        let per_elem_size = TYPE_FIXED_SIZE
+            .get(k_type as usize)
+            .expect("unexpect ttype")
+            + TYPE_FIXED_SIZE.get(v_type as usize).expect("unexpect ttype");
+        self.charge_container_len(size, per_elem_size.max(1))?;
+        Ok((k_type, v_type, Some(size)))
     }
 
     #[inline]
@@ -527,7 +719,12 @@ impl<B: BufExt> ProtocolReader for BinaryProtocolDeserializer<B> {
         let elem_type = TType::try_from(self.read_byte()?)?;
         let size = self.read_i32()?;
         ensure_err!(size >= 0, ProtocolError::InvalidDataLength);
-        Ok((elem_type, Some(size as usize)))
+        let size = size as usize;
+        let per_elem_size = *TYPE_FIXED_SIZE
+            .get(elem_type as usize)
+            .expect("unexpect ttype");
+        self.charge_container_len(size, per_elem_size.max(1))?;
+        Ok((elem_type, Some(size)))
     }
 
     #[inline]
@@ -548,7 +745,12 @@ impl<B: BufExt> ProtocolReader for BinaryProtocolDeserializer<B> {
         let elem_type = TType::try_from(self.read_byte()?)?;
         let size = self.read_i32()?;
         ensure_err!(size >= 0, ProtocolError::InvalidDataLength);
-        Ok((elem_type, Some(size as usize)))
+        let size = size as usize;
+        let per_elem_size = *TYPE_FIXED_SIZE
+            .get(elem_type as usize)
+            .expect("unexpect ttype");
+        self.charge_container_len(size, per_elem_size.max(1))?;
+        Ok((elem_type, Some(size)))
     }
 
     #[inline]
@@ -622,6 +824,7 @@ impl<B: BufExt> ProtocolReader for BinaryProtocolDeserializer<B> {
         let received_len = received_len as usize;
 
         ensure_err!(self.buffer.remaining() >= received_len, ProtocolError::EOF);
+        self.charge_bytes(received_len)?;
         Ok(V::copy_from_buf(&mut self.buffer, received_len))
     }
 
@@ -650,12 +853,96 @@ impl<B: BufExt> ProtocolReader for BinaryProtocolDeserializer<B> {
         self.buffer.can_advance(bytes)
     }
 
+    // Routed through `try_skip` (rather than a separate eager skip loop) so
+    // that a short read on a partial frame leaves `resume_stack` populated:
+    // the caller can append more bytes to the same buffer and call `skip`
+    // again to continue exactly where this call left off, instead of
+    // restarting the unknown field from scratch.
     #[inline]
     fn skip(&mut self, field_type: TType) -> Result<()> {
-        thread_local! {
-            static STACK: RefCell<[SkipData; DEFAULT_RECURSION_DEPTH as usize]> = const {RefCell::new([SkipData::Next(TType::Void); DEFAULT_RECURSION_DEPTH as usize])};
+        match self.try_skip(field_type)? {
+            DeserializeOutcome::Done(()) => Ok(()),
+            DeserializeOutcome::NeedMore { .. } => bail_err!(ProtocolError::EOF),
         }
-        STACK.with_borrow_mut(|stack| self.skip_fast(field_type, stack))
+    }
+}
+
+impl<B: BufExt> BinaryProtocolDeserializer<B> {
+    #[inline]
+    pub fn read_i128(&mut self) -> Result<i128> {
+        let bytes = self.read_binary::<Vec<u8>>()?;
+        let array: [u8; 16] = bytes
+            .try_into()
+            .map_err(|_| ProtocolError::InvalidDataLength)?;
+        Ok(i128::from_be_bytes(array))
+    }
+    #[inline]
+    pub fn read_u128(&mut self) -> Result<u128> {
+        let bytes = self.read_binary::<Vec<u8>>()?;
+        let array: [u8; 16] = bytes
+            .try_into()
+            .map_err(|_| ProtocolError::InvalidDataLength)?;
+        Ok(u128::from_be_bytes(array))
+    }
+
+    #[inline]
+    pub fn read_i128_le(&mut self) -> Result<i128> {
+        let bytes = self.read_binary::<Vec<u8>>()?;
+        let array: [u8; 16] = bytes
+            .try_into()
+            .map_err(|_| ProtocolError::InvalidDataLength)?;
+        Ok(i128::from_le_bytes(array))
+    }
+    #[inline]
+    pub fn read_u128_le(&mut self) -> Result<u128> {
+        let bytes = self.read_binary::<Vec<u8>>()?;
+        let array: [u8; 16] = bytes
+            .try_into()
+            .map_err(|_| ProtocolError::InvalidDataLength)?;
+        Ok(u128::from_le_bytes(array))
+    }
+
+    #[inline]
+    pub fn read_i128_compressed(&mut self) -> Result<i128> {
+        let bytes = self.read_binary::<Vec<u8>>()?;
+        ensure_err!(
+            !bytes.is_empty() && bytes.len() <= 16,
+            ProtocolError::InvalidDataLength
+        );
+        let sign = if bytes[0] & 0x80 != 0 { 0xffu8 } else { 0x00u8 };
+        let mut array = [sign; 16];
+        array[16 - bytes.len()..].copy_from_slice(&bytes);
+        Ok(i128::from_be_bytes(array))
+    }
+    #[inline]
+    pub fn read_u128_compressed(&mut self) -> Result<u128> {
+        let bytes = self.read_binary::<Vec<u8>>()?;
+        ensure_err!(
+            !bytes.is_empty() && bytes.len() <= 16,
+            ProtocolError::InvalidDataLength
+        );
+        let mut array = [0u8; 16];
+        array[16 - bytes.len()..].copy_from_slice(&bytes);
+        Ok(u128::from_be_bytes(array))
+    }
+
+    #[cfg(feature = "int256")]
+    #[inline]
+    pub fn read_u256(&mut self) -> Result<ethnum::U256> {
+        let bytes = self.read_binary::<Vec<u8>>()?;
+        let array: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| ProtocolError::InvalidDataLength)?;
+        Ok(ethnum::U256::from_be_bytes(array))
+    }
+    #[cfg(feature = "int256")]
+    #[inline]
+    pub fn read_u256_le(&mut self) -> Result<ethnum::U256> {
+        let bytes = self.read_binary::<Vec<u8>>()?;
+        let array: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| ProtocolError::InvalidDataLength)?;
+        Ok(ethnum::U256::from_le_bytes(array))
     }
 }
 
@@ -736,3 +1023,152 @@ where
     let mut deser = BinaryProtocolDeserializer::new(source.0);
     T::rs_thrift_read(&mut deser)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_of_i64_charges_real_element_size() {
+        // A list<i64> claiming 1000 elements should be charged 8 bytes per
+        // element, not a flat 1, so a budget sized for the real i64 payload
+        // rejects a claimed length that would actually need far more.
+        let mut ser = BinaryProtocolSerializer::with_buffer(BytesMut::new());
+        ser.write_list_begin(TType::I64, 1000);
+        let bytes = ser.finish();
+
+        let limits = DeserializeLimits {
+            max_total_bytes: Some(1000 * 8 - 1),
+            ..DeserializeLimits::default()
+        };
+        let mut de =
+            BinaryProtocolDeserializer::with_limits(Cursor::new(bytes.as_ref()), limits);
+        assert!(de.read_list_begin_unchecked().is_err());
+
+        let limits = DeserializeLimits {
+            max_total_bytes: Some(1000 * 8),
+            ..DeserializeLimits::default()
+        };
+        let mut de =
+            BinaryProtocolDeserializer::with_limits(Cursor::new(bytes.as_ref()), limits);
+        assert!(de.read_list_begin_unchecked().is_ok());
+    }
+
+    #[test]
+    fn container_len_over_max_is_rejected() {
+        let mut ser = BinaryProtocolSerializer::with_buffer(BytesMut::new());
+        ser.write_set_begin(TType::Byte, 10);
+        let bytes = ser.finish();
+
+        let limits = DeserializeLimits {
+            max_container_len: Some(9),
+            ..DeserializeLimits::default()
+        };
+        let mut de =
+            BinaryProtocolDeserializer::with_limits(Cursor::new(bytes.as_ref()), limits);
+        assert!(de.read_set_begin_unchecked().is_err());
+    }
+
+    #[test]
+    fn default_limits_impose_no_budget() {
+        let mut ser = BinaryProtocolSerializer::with_buffer(BytesMut::new());
+        ser.write_list_begin(TType::I64, 1_000_000);
+        let bytes = ser.finish();
+
+        let mut de = BinaryProtocolDeserializer::new(Cursor::new(bytes.as_ref()));
+        assert!(de.read_list_begin_unchecked().is_ok());
+    }
+
+    #[test]
+    fn i128_and_u128_roundtrip_both_endiannesses() {
+        let mut ser = BinaryProtocolSerializer::with_buffer(BytesMut::new());
+        ser.write_i128(-42);
+        let bytes = ser.finish();
+        let mut de = BinaryProtocolDeserializer::new(Cursor::new(bytes.as_ref()));
+        assert_eq!(de.read_i128().unwrap(), -42);
+
+        let mut ser = BinaryProtocolSerializer::with_buffer(BytesMut::new());
+        ser.write_u128_le(u128::MAX);
+        let bytes = ser.finish();
+        let mut de = BinaryProtocolDeserializer::new(Cursor::new(bytes.as_ref()));
+        assert_eq!(de.read_u128_le().unwrap(), u128::MAX);
+    }
+
+    #[test]
+    fn i128_compressed_strips_redundant_sign_bytes() {
+        for value in [0i128, 1, -1, 127, -128, 12345, -12345, i128::MAX, i128::MIN] {
+            let mut ser = BinaryProtocolSerializer::with_buffer(BytesMut::new());
+            ser.write_i128_compressed(value);
+            let bytes = ser.finish();
+            // A small value should collapse to far fewer than the full 16
+            // bytes; the extremes still round-trip exactly.
+            if value == 0 || value == -1 {
+                assert_eq!(bytes.len(), 4 + 1);
+            }
+            let mut de = BinaryProtocolDeserializer::new(Cursor::new(bytes.as_ref()));
+            assert_eq!(de.read_i128_compressed().unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn u128_compressed_strips_leading_zero_bytes() {
+        for value in [0u128, 1, 127, 255, 12345, u128::MAX] {
+            let mut ser = BinaryProtocolSerializer::with_buffer(BytesMut::new());
+            ser.write_u128_compressed(value);
+            let bytes = ser.finish();
+            if value == 0 {
+                assert_eq!(bytes.len(), 4 + 1);
+            }
+            let mut de = BinaryProtocolDeserializer::new(Cursor::new(bytes.as_ref()));
+            assert_eq!(de.read_u128_compressed().unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn try_skip_needs_more_on_a_partial_frame_then_resumes() {
+        let mut ser = BinaryProtocolSerializer::with_buffer(BytesMut::new());
+        ser.write_i32(42);
+        let bytes = ser.finish();
+
+        // Only the first 2 of the 4 i32 bytes are available up front.
+        let mut de = BinaryProtocolDeserializer::new(Cursor::new(bytes[..2].to_vec()));
+        match de.try_skip(TType::I32).unwrap() {
+            DeserializeOutcome::NeedMore { .. } => {}
+            DeserializeOutcome::Done(()) => panic!("expected NeedMore on a short buffer"),
+        }
+        assert!(de.resume_stack.is_some());
+
+        // Append the rest of the frame to the same buffer and resume the
+        // same skip; it should pick up from the saved stack rather than
+        // starting over.
+        de.buffer.get_mut().extend_from_slice(&bytes[2..]);
+        match de.try_skip(TType::I32).unwrap() {
+            DeserializeOutcome::Done(()) => {}
+            DeserializeOutcome::NeedMore { .. } => panic!("expected Done once the frame is complete"),
+        }
+        assert!(de.resume_stack.is_none());
+    }
+
+    #[test]
+    fn skip_surfaces_eof_on_a_short_buffer() {
+        let mut ser = BinaryProtocolSerializer::with_buffer(BytesMut::new());
+        ser.write_i32(42);
+        let bytes = ser.finish();
+
+        let mut de = BinaryProtocolDeserializer::new(Cursor::new(&bytes[..2]));
+        assert!(de.skip(TType::I32).is_err());
+    }
+
+    #[test]
+    fn skip_consumes_a_complete_struct() {
+        let mut ser = BinaryProtocolSerializer::with_buffer(BytesMut::new());
+        ser.write_field_begin("", TType::I16, 1);
+        ser.write_i16(7);
+        ser.write_field_stop();
+        let bytes = ser.finish();
+
+        let mut de = BinaryProtocolDeserializer::new(Cursor::new(bytes.as_ref()));
+        assert!(de.skip(TType::Struct).is_ok());
+        assert_eq!(de.buffer.remaining(), 0);
+    }
+}
@@ -0,0 +1,1045 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::cell::RefCell;
+use std::io::Cursor;
+
+use anyhow::Result;
+use anyhow::anyhow;
+use bufsize::SizeCounter;
+use bytes::Bytes;
+use bytes::BytesMut;
+use ghost::phantom;
+
+use crate::binary_protocol::DeserializeLimits;
+use crate::binary_type::CopyFromBuf;
+use crate::binary_type::Discard;
+use crate::bufext::BufExt;
+use crate::bufext::BufMutExt;
+use crate::bufext::DeserializeSource;
+use crate::deserialize::Deserialize;
+use crate::errors::ProtocolError;
+use crate::framing::Framing;
+use crate::protocol::DEFAULT_RECURSION_DEPTH;
+use crate::protocol::Field;
+use crate::protocol::Protocol;
+use crate::protocol::ProtocolReader;
+use crate::protocol::ProtocolWriter;
+use crate::serialize::Serialize;
+use crate::thrift_protocol::MessageType;
+use crate::thrift_protocol::ProtocolID;
+use crate::ttype::GetTType;
+use crate::ttype::TType;
+
+pub const COMPACT_VERSION: u8 = 1;
+pub const COMPACT_VERSION_MASK: u8 = 0x1f;
+pub const COMPACT_PROTOCOL_ID: u8 = 0x82;
+
+const COMPACT_BOOLEAN_TRUE: u8 = 1;
+const COMPACT_BOOLEAN_FALSE: u8 = 2;
+const COMPACT_BYTE: u8 = 3;
+const COMPACT_I16: u8 = 4;
+const COMPACT_I32: u8 = 5;
+const COMPACT_I64: u8 = 6;
+const COMPACT_DOUBLE: u8 = 7;
+const COMPACT_BINARY: u8 = 8;
+const COMPACT_LIST: u8 = 9;
+const COMPACT_SET: u8 = 10;
+const COMPACT_MAP: u8 = 11;
+const COMPACT_STRUCT: u8 = 12;
+const COMPACT_FLOAT: u8 = 13;
+
+/// A space-efficient format compatible with Apache/FB Thrift's TCompactProtocol.
+///
+/// ```ignore
+/// let protocol = CompactProtocol;
+/// let transport = HttpClient::new(ENDPOINT)?;
+/// let client = <dyn BuckGraphService>::new(protocol, transport);
+/// ```
+///
+/// Integers are zigzag+varint encoded and struct field ids are delta-encoded
+/// against the previous field in the same struct, which typically shaves a
+/// large fraction off the wire size of `BinaryProtocol` for small messages.
+///
+/// The type parameter is the Framing expected by the transport on which this
+/// protocol is operating, exactly as with `BinaryProtocol`.
+#[phantom]
+#[derive(Copy, Clone)]
+pub struct CompactProtocol<F = Bytes>;
+
+pub struct CompactProtocolSerializer<B> {
+    buffer: B,
+    // Stack of the last field id written in each currently-open struct.
+    last_field_id: Vec<i16>,
+    // A bool field defers writing its value byte until `write_bool` is
+    // called, so the value can be packed into the field-header type nibble.
+    pending_bool_field_id: Option<i16>,
+}
+
+pub struct CompactProtocolDeserializer<B> {
+    buffer: B,
+    last_field_id: Vec<i16>,
+    // The value of a bool field read alongside its header, returned by the
+    // next call to `read_bool`.
+    pending_bool_value: Option<bool>,
+    limits: DeserializeLimits,
+    remaining_bytes: Option<usize>,
+}
+
+impl<F> Protocol for CompactProtocol<F>
+where
+    F: Framing + 'static,
+{
+    type Frame = F;
+    type Sizer = CompactProtocolSerializer<SizeCounter>;
+    type Serializer = CompactProtocolSerializer<F::EncBuf>;
+    type Deserializer = CompactProtocolDeserializer<F::DecBuf>;
+
+    const PROTOCOL_ID: ProtocolID = ProtocolID::CompactProtocol;
+    #[inline]
+    fn serializer<SZ, SER>(size: SZ, ser: SER) -> <Self::Serializer as ProtocolWriter>::Final
+    where
+        SZ: FnOnce(&mut Self::Sizer),
+        SER: FnOnce(&mut Self::Serializer),
+    {
+        let mut sizer = CompactProtocolSerializer::with_buffer(SizeCounter::new());
+        size(&mut sizer);
+        let sz = sizer.finish();
+        let mut buf = CompactProtocolSerializer::with_buffer(F::enc_with_capacity(sz));
+        ser(&mut buf);
+        buf.finish()
+    }
+    #[inline]
+    fn deserializer(buf: F::DecBuf) -> Self::Deserializer {
+        CompactProtocolDeserializer::new(buf)
+    }
+    #[inline]
+    fn into_buffer(deser: Self::Deserializer) -> F::DecBuf {
+        deser.into_inner()
+    }
+}
+
+impl<B> CompactProtocolSerializer<B> {
+    #[inline]
+    pub fn with_buffer(buffer: B) -> Self {
+        Self {
+            buffer,
+            last_field_id: Vec::new(),
+            pending_bool_field_id: None,
+        }
+    }
+}
+
+impl<B: BufMutExt> CompactProtocolSerializer<B> {
+    #[inline]
+    fn write_varint(&mut self, mut value: u64) {
+        loop {
+            if value < 0x80 {
+                self.buffer.put_u8(value as u8);
+                break;
+            }
+            self.buffer.put_u8(((value & 0x7f) | 0x80) as u8);
+            value >>= 7;
+        }
+    }
+
+    #[inline]
+    fn write_zigzag(&mut self, value: i64) {
+        self.write_varint(zigzag(value));
+    }
+
+    fn write_collection_begin(&mut self, elem_type: TType, size: usize) {
+        let elem_code = compact_elem_type(elem_type).unwrap_or_else(|e| panic!("{e}"));
+        if size < 15 {
+            self.buffer.put_u8(((size as u8) << 4) | elem_code);
+        } else {
+            self.buffer.put_u8(0xf0 | elem_code);
+            self.write_varint(size as u64);
+        }
+    }
+}
+
+#[inline]
+fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+#[inline]
+fn unzigzag(n: u64) -> i64 {
+    ((n >> 1) as i64) ^ -((n & 1) as i64)
+}
+
+/// Maps a `TType` to its compact wire type code, for contexts (struct field
+/// headers) where a bool's value isn't yet known and must be filled in by
+/// `write_bool`/`read_bool` using `COMPACT_BOOLEAN_TRUE`/`COMPACT_BOOLEAN_FALSE`.
+fn compact_field_type(field_type: TType) -> Result<u8> {
+    match field_type {
+        TType::Bool => Ok(COMPACT_BOOLEAN_TRUE),
+        other => compact_elem_type(other),
+    }
+}
+
+/// Maps a `TType` to its compact wire type code for collection elements,
+/// where bool values are instead carried as standalone 1/2 bytes.
+///
+/// Returns `ProtocolError::StreamUnsupported` for `TType::Stream`. The
+/// serializer's write methods cannot return an error, so they panic with
+/// this error's message.
+fn compact_elem_type(elem_type: TType) -> Result<u8> {
+    Ok(match elem_type {
+        TType::Bool => COMPACT_BOOLEAN_TRUE,
+        TType::Byte => COMPACT_BYTE,
+        TType::I16 => COMPACT_I16,
+        TType::I32 => COMPACT_I32,
+        TType::I64 => COMPACT_I64,
+        TType::Double => COMPACT_DOUBLE,
+        TType::Float => COMPACT_FLOAT,
+        TType::String | TType::UTF8 | TType::UTF16 => COMPACT_BINARY,
+        TType::List => COMPACT_LIST,
+        TType::Set => COMPACT_SET,
+        TType::Map => COMPACT_MAP,
+        TType::Struct => COMPACT_STRUCT,
+        TType::Stream => bail_err!(ProtocolError::StreamUnsupported),
+        other => unreachable!("unexpected ttype in compact protocol: {:?}", other),
+    })
+}
+
+/// Rough in-memory size charged per element when bounding
+/// `DeserializeLimits::max_total_bytes`, mirroring
+/// `binary_protocol::TYPE_FIXED_SIZE`. Variable-length types (strings,
+/// nested containers) are charged through their own `read_binary`/
+/// `read_collection_begin` call instead, so they're 0 here.
+const TYPE_FIXED_SIZE: [usize; 20] = [
+    0, // TType::Stop
+    0, // TType::Void
+    1, // TType::Bool
+    1, // TType::Byte
+    8, // TType::Double
+    0, // NAN
+    2, // TType::I16
+    0, // NAN
+    4, // TType::I32
+    0, // NAN
+    8, // TType::I64
+    0, // TType::String
+    0, // TType::Struct
+    0, // TType::Map
+    0, // TType::Set
+    0, // TType::List
+    0, // TType::UTF8
+    0, // TType::UTF16
+    0, // TType::Stream
+    4, // TType::Float
+];
+
+fn ttype_from_compact(code: u8) -> Result<TType> {
+    Ok(match code {
+        0 => TType::Stop,
+        COMPACT_BOOLEAN_TRUE | COMPACT_BOOLEAN_FALSE => TType::Bool,
+        COMPACT_BYTE => TType::Byte,
+        COMPACT_I16 => TType::I16,
+        COMPACT_I32 => TType::I32,
+        COMPACT_I64 => TType::I64,
+        COMPACT_DOUBLE => TType::Double,
+        COMPACT_BINARY => TType::String,
+        COMPACT_LIST => TType::List,
+        COMPACT_SET => TType::Set,
+        COMPACT_MAP => TType::Map,
+        COMPACT_STRUCT => TType::Struct,
+        COMPACT_FLOAT => TType::Float,
+        _ => bail_err!(ProtocolError::InvalidTypeId),
+    })
+}
+
+impl<B: BufExt> CompactProtocolDeserializer<B> {
+    #[inline]
+    pub fn new(buffer: B) -> Self {
+        Self::with_limits(buffer, DeserializeLimits::default())
+    }
+
+    /// Build a deserializer that enforces `limits` on container sizes,
+    /// total allocated bytes, and skip recursion depth, rejecting untrusted
+    /// input with `ProtocolError` instead of acting on wire-supplied sizes
+    /// unconditionally. Mirrors `BinaryProtocolDeserializer::with_limits`.
+    #[inline]
+    pub fn with_limits(buffer: B, limits: DeserializeLimits) -> Self {
+        let remaining_bytes = limits.max_total_bytes;
+        CompactProtocolDeserializer {
+            buffer,
+            last_field_id: Vec::new(),
+            pending_bool_value: None,
+            limits,
+            remaining_bytes,
+        }
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> B {
+        self.buffer
+    }
+
+    /// Deduct `len` bytes from the configured total-bytes budget, if any,
+    /// failing closed when the budget is exhausted.
+    #[inline]
+    fn charge_bytes(&mut self, len: usize) -> Result<()> {
+        if let Some(remaining) = self.remaining_bytes.as_mut() {
+            ensure_err!(*remaining >= len, ProtocolError::SizeLimitExceeded);
+            *remaining -= len;
+        }
+        Ok(())
+    }
+
+    /// Validate a wire-supplied container element count against the
+    /// configured limit and charge its rough memory cost against the
+    /// total-bytes budget.
+    #[inline]
+    fn charge_container_len(&mut self, len: usize, per_elem_size: usize) -> Result<()> {
+        if let Some(max_len) = self.limits.max_container_len {
+            ensure_err!(len <= max_len, ProtocolError::SizeLimitExceeded);
+        }
+        self.charge_bytes(len.saturating_mul(per_elem_size))
+    }
+
+    fn read_varint(&mut self) -> Result<u64> {
+        let mut result: u64 = 0;
+        let mut shift = 0u32;
+        loop {
+            let byte = self.read_raw_byte()?;
+            result |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+            shift += 7;
+            ensure_err!(shift < 64, ProtocolError::InvalidDataLength);
+        }
+        Ok(result)
+    }
+
+    #[inline]
+    fn read_zigzag(&mut self) -> Result<i64> {
+        Ok(unzigzag(self.read_varint()?))
+    }
+
+    #[inline]
+    fn read_raw_byte(&mut self) -> Result<u8> {
+        ensure_err!(self.buffer.remaining() >= 1, ProtocolError::EOF);
+        Ok(self.buffer.get_u8())
+    }
+
+    fn read_collection_begin(&mut self) -> Result<(TType, usize)> {
+        let header = self.read_raw_byte()?;
+        let elem_type = ttype_from_compact(header & 0x0f)?;
+        let size_nibble = header >> 4;
+        let size = if size_nibble != 0x0f {
+            size_nibble as usize
+        } else {
+            self.read_varint()?
+                .try_into()
+                .map_err(|_| ProtocolError::InvalidDataLength)?
+        };
+        let per_elem_size = *TYPE_FIXED_SIZE
+            .get(elem_type as usize)
+            .expect("unexpect ttype");
+        self.charge_container_len(size, per_elem_size.max(1))?;
+        Ok((elem_type, size))
+    }
+
+    fn skip_fast(&mut self, field_type: TType, stack: &mut [CompactSkipData]) -> Result<()> {
+        let mut stack_len: usize = 0;
+        macro_rules! pop {
+            () => {
+                match stack_len.checked_sub(1) {
+                    Some(last) => {
+                        stack_len = last;
+                        stack[last]
+                    }
+                    None => break,
+                }
+            };
+        }
+        macro_rules! push {
+            ($elem: expr) => {
+                if stack_len >= stack.len() {
+                    bail_err!(ProtocolError::SkipDepthExceeded);
+                }
+                stack[stack_len] = $elem;
+                stack_len += 1;
+            };
+        }
+
+        let mut current = CompactSkipData::Next(field_type);
+        loop {
+            match current {
+                CompactSkipData::Next(ttype) => {
+                    match ttype {
+                        TType::Bool => {
+                            self.read_bool()?;
+                            current = pop!();
+                        }
+                        TType::Byte => {
+                            self.read_byte()?;
+                            current = pop!();
+                        }
+                        TType::I16 => {
+                            self.read_i16()?;
+                            current = pop!();
+                        }
+                        TType::I32 => {
+                            self.read_i32()?;
+                            current = pop!();
+                        }
+                        TType::I64 => {
+                            self.read_i64()?;
+                            current = pop!();
+                        }
+                        TType::Double => {
+                            self.read_double()?;
+                            current = pop!();
+                        }
+                        TType::Float => {
+                            self.read_float()?;
+                            current = pop!();
+                        }
+                        TType::String | TType::UTF8 | TType::UTF16 => {
+                            self.read_binary::<Discard>()?;
+                            current = pop!();
+                        }
+                        TType::Struct => {
+                            self.last_field_id.push(0);
+                            let (_, field_type, _) = self.read_field_begin(|_| (), &[])?;
+                            match field_type {
+                                TType::Stop => {
+                                    self.last_field_id.pop();
+                                    current = pop!();
+                                }
+                                _ => {
+                                    push!(CompactSkipData::StructField);
+                                    current = CompactSkipData::Next(field_type);
+                                }
+                            }
+                        }
+                        TType::List | TType::Set => {
+                            let (elem_type, len) = self.read_collection_begin()?;
+                            current = CompactSkipData::Collection(len as u32, [elem_type, elem_type]);
+                        }
+                        TType::Map => {
+                            let (key_type, val_type, size) = self.read_map_begin_unchecked()?;
+                            let len = size.unwrap_or(0) as u32;
+                            current = CompactSkipData::Collection(len * 2, [key_type, val_type]);
+                        }
+                        TType::Void | TType::Stop => {
+                            current = pop!();
+                        }
+                        TType::Stream => bail_err!(ProtocolError::StreamUnsupported),
+                    }
+                }
+                CompactSkipData::StructField => {
+                    let (_, field_type, _) = self.read_field_begin(|_| (), &[])?;
+                    match field_type {
+                        TType::Stop => {
+                            self.last_field_id.pop();
+                            current = pop!();
+                        }
+                        _ => {
+                            push!(CompactSkipData::StructField);
+                            current = CompactSkipData::Next(field_type);
+                        }
+                    }
+                }
+                CompactSkipData::Collection(len, ttypes) => {
+                    if len == 0 {
+                        current = pop!();
+                        continue;
+                    }
+                    current = CompactSkipData::Next(ttypes[(len & 1) as usize]);
+                    push!(CompactSkipData::Collection(len - 1, ttypes));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+enum CompactSkipData {
+    Collection(u32, [TType; 2]),
+    StructField,
+    Next(TType),
+}
+
+impl<B: BufMutExt> ProtocolWriter for CompactProtocolSerializer<B> {
+    type Final = B::Final;
+    #[inline]
+    fn write_message_begin(&mut self, name: &str, type_id: MessageType, seqid: u32) {
+        self.buffer.put_u8(COMPACT_PROTOCOL_ID);
+        self.buffer
+            .put_u8((COMPACT_VERSION & COMPACT_VERSION_MASK) | ((type_id as u8) << 5));
+        self.write_varint(seqid as u64);
+        self.write_varint(name.len() as u64);
+        self.buffer.put_slice(name.as_bytes());
+    }
+
+    #[inline]
+    fn write_message_end(&mut self) {}
+
+    #[inline]
+    fn write_struct_begin(&mut self, _name: &str) {
+        self.last_field_id.push(0);
+    }
+
+    #[inline]
+    fn write_struct_end(&mut self) {
+        self.last_field_id.pop();
+    }
+    #[inline]
+    fn write_field_begin(&mut self, _name: &str, type_id: TType, id: i16) {
+        if type_id == TType::Bool {
+            self.pending_bool_field_id = Some(id);
+            return;
+        }
+        self.write_field_header(compact_field_type(type_id).unwrap_or_else(|e| panic!("{e}")), id);
+    }
+
+    #[inline]
+    fn write_field_end(&mut self) {}
+
+    #[inline]
+    fn write_field_stop(&mut self) {
+        self.buffer.put_u8(0);
+    }
+    #[inline]
+    fn write_map_begin(&mut self, key_type: TType, value_type: TType, size: usize) {
+        if size == 0 {
+            self.buffer.put_u8(0);
+        } else {
+            self.write_varint(size as u64);
+            let key_code = compact_elem_type(key_type).unwrap_or_else(|e| panic!("{e}"));
+            let value_code = compact_elem_type(value_type).unwrap_or_else(|e| panic!("{e}"));
+            self.buffer.put_u8((key_code << 4) | value_code);
+        }
+    }
+
+    #[inline]
+    fn write_map_key_begin(&mut self) {}
+
+    #[inline]
+    fn write_map_value_begin(&mut self) {}
+
+    #[inline]
+    fn write_map_end(&mut self) {}
+    #[inline]
+    fn write_list_begin(&mut self, elem_type: TType, size: usize) {
+        self.write_collection_begin(elem_type, size);
+    }
+
+    #[inline]
+    fn write_list_value_begin(&mut self) {}
+
+    #[inline]
+    fn write_list_end(&mut self) {}
+    #[inline]
+    fn write_set_begin(&mut self, elem_type: TType, size: usize) {
+        self.write_collection_begin(elem_type, size);
+    }
+
+    #[inline]
+    fn write_set_value_begin(&mut self) {}
+    #[inline]
+    fn write_set_end(&mut self) {}
+    #[inline]
+    fn write_bool(&mut self, value: bool) {
+        let code = if value {
+            COMPACT_BOOLEAN_TRUE
+        } else {
+            COMPACT_BOOLEAN_FALSE
+        };
+        if let Some(id) = self.pending_bool_field_id.take() {
+            self.write_field_header(code, id);
+        } else {
+            self.buffer.put_u8(code);
+        }
+    }
+    #[inline]
+    fn write_byte(&mut self, value: i8) {
+        self.buffer.put_i8(value)
+    }
+    #[inline]
+    fn write_i16(&mut self, value: i16) {
+        self.write_zigzag(value as i64);
+    }
+    #[inline]
+    fn write_i32(&mut self, value: i32) {
+        self.write_zigzag(value as i64);
+    }
+    #[inline]
+    fn write_i64(&mut self, value: i64) {
+        self.write_zigzag(value);
+    }
+    #[inline]
+    fn write_double(&mut self, value: f64) {
+        self.buffer.put_slice(&value.to_le_bytes());
+    }
+    #[inline]
+    fn write_float(&mut self, value: f32) {
+        self.buffer.put_slice(&value.to_le_bytes());
+    }
+    #[inline]
+    fn write_string(&mut self, value: &str) {
+        self.write_varint(value.len() as u64);
+        self.buffer.put_slice(value.as_bytes())
+    }
+    #[inline]
+    fn write_binary(&mut self, value: &[u8]) {
+        self.write_varint(value.len() as u64);
+        self.buffer.put_slice(value)
+    }
+    #[inline]
+    fn finish(self) -> B::Final {
+        self.buffer.finalize()
+    }
+}
+
+impl<B: BufMutExt> CompactProtocolSerializer<B> {
+    fn write_field_header(&mut self, type_code: u8, id: i16) {
+        let last_id = *self.last_field_id.last().unwrap_or(&0);
+        let delta = id.wrapping_sub(last_id);
+        if delta > 0 && delta <= 15 {
+            self.buffer.put_u8(((delta as u8) << 4) | type_code);
+        } else {
+            self.buffer.put_u8(type_code);
+            self.write_zigzag(id as i64);
+        }
+        if let Some(last) = self.last_field_id.last_mut() {
+            *last = id;
+        }
+    }
+}
+
+impl<B: BufExt> ProtocolReader for CompactProtocolDeserializer<B> {
+    #[inline]
+    fn read_message_begin<F, T>(&mut self, msgfn: F) -> Result<(T, MessageType, u32)>
+    where
+        F: FnOnce(&[u8]) -> T,
+    {
+        let protocol_id = self.read_raw_byte()?;
+        ensure_err!(
+            protocol_id == COMPACT_PROTOCOL_ID,
+            ProtocolError::BadVersion
+        );
+        let version_and_type = self.read_raw_byte()?;
+        let version = version_and_type & COMPACT_VERSION_MASK;
+        ensure_err!(version == COMPACT_VERSION, ProtocolError::BadVersion);
+        let msgtype = MessageType::try_from((version_and_type >> 5) as u32)?;
+        let seq_id = self.read_varint()? as u32;
+        let len: usize = self
+            .read_varint()?
+            .try_into()
+            .map_err(|_| ProtocolError::InvalidDataLength)?;
+        ensure_err!(self.buffer.remaining() >= len, ProtocolError::EOF);
+        let namebuf: Vec<u8> = Vec::copy_from_buf(&mut self.buffer, len);
+        let name = msgfn(namebuf.as_slice());
+
+        Ok((name, msgtype, seq_id))
+    }
+    #[inline]
+    fn read_message_end(&mut self) -> Result<()> {
+        Ok(())
+    }
+    #[inline]
+    fn read_struct_begin<F, T>(&mut self, namefn: F) -> Result<T>
+    where
+        F: FnOnce(&[u8]) -> T,
+    {
+        self.last_field_id.push(0);
+        Ok(namefn(&[]))
+    }
+    #[inline]
+    fn read_struct_end(&mut self) -> Result<()> {
+        self.last_field_id.pop();
+        Ok(())
+    }
+    #[inline]
+    fn read_field_begin<F, T>(&mut self, fieldfn: F, _fields: &[Field]) -> Result<(T, TType, i16)>
+    where
+        F: FnOnce(&[u8]) -> T,
+    {
+        let header = self.read_raw_byte()?;
+        if header == 0 {
+            return Ok((fieldfn(&[]), TType::Stop, 0));
+        }
+        let delta = header >> 4;
+        let type_code = header & 0x0f;
+        let field_type = ttype_from_compact(type_code)?;
+        let last_id = *self.last_field_id.last().unwrap_or(&0);
+        let id = if delta != 0 {
+            last_id.wrapping_add(delta as i16)
+        } else {
+            self.read_zigzag()? as i16
+        };
+        if let Some(last) = self.last_field_id.last_mut() {
+            *last = id;
+        }
+        self.pending_bool_value = match type_code {
+            COMPACT_BOOLEAN_TRUE => Some(true),
+            COMPACT_BOOLEAN_FALSE => Some(false),
+            _ => None,
+        };
+        Ok((fieldfn(&[]), field_type, id))
+    }
+    #[inline]
+    fn read_field_end(&mut self) -> Result<()> {
+        Ok(())
+    }
+    #[inline]
+    fn read_map_begin_unchecked(&mut self) -> Result<(TType, TType, Option<usize>)> {
+        let size: usize = self
+            .read_varint()?
+            .try_into()
+            .map_err(|_| ProtocolError::InvalidDataLength)?;
+        if size == 0 {
+            return Ok((TType::Stop, TType::Stop, Some(0)));
+        }
+        let types = self.read_raw_byte()?;
+        let key_type = ttype_from_compact(types >> 4)?;
+        let val_type = ttype_from_compact(types & 0x0f)?;
+        let per_elem_size = TYPE_FIXED_SIZE
+            .get(key_type as usize)
+            .expect("unexpect ttype")
+            + TYPE_FIXED_SIZE.get(val_type as usize).expect("unexpect ttype");
+        self.charge_container_len(size, per_elem_size.max(1))?;
+        Ok((key_type, val_type, Some(size)))
+    }
+
+    #[inline]
+    fn read_map_key_begin(&mut self) -> Result<bool> {
+        Ok(true)
+    }
+
+    #[inline]
+    fn read_map_value_begin(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    #[inline]
+    fn read_map_value_end(&mut self) -> Result<()> {
+        Ok(())
+    }
+    #[inline]
+    fn read_map_end(&mut self) -> Result<()> {
+        Ok(())
+    }
+    #[inline]
+    fn read_list_begin_unchecked(&mut self) -> Result<(TType, Option<usize>)> {
+        let (elem_type, size) = self.read_collection_begin()?;
+        Ok((elem_type, Some(size)))
+    }
+
+    #[inline]
+    fn read_list_value_begin(&mut self) -> Result<bool> {
+        Ok(true)
+    }
+
+    #[inline]
+    fn read_list_value_end(&mut self) -> Result<()> {
+        Ok(())
+    }
+    #[inline]
+    fn read_list_end(&mut self) -> Result<()> {
+        Ok(())
+    }
+    #[inline]
+    fn read_set_begin_unchecked(&mut self) -> Result<(TType, Option<usize>)> {
+        let (elem_type, size) = self.read_collection_begin()?;
+        Ok((elem_type, Some(size)))
+    }
+
+    #[inline]
+    fn read_set_value_begin(&mut self) -> Result<bool> {
+        Ok(true)
+    }
+
+    #[inline]
+    fn read_set_value_end(&mut self) -> Result<()> {
+        Ok(())
+    }
+    #[inline]
+    fn read_set_end(&mut self) -> Result<()> {
+        Ok(())
+    }
+    #[inline]
+    fn read_bool(&mut self) -> Result<bool> {
+        if let Some(value) = self.pending_bool_value.take() {
+            return Ok(value);
+        }
+        Ok(self.read_raw_byte()? == COMPACT_BOOLEAN_TRUE)
+    }
+    #[inline]
+    fn read_byte(&mut self) -> Result<i8> {
+        ensure_err!(self.buffer.remaining() >= 1, ProtocolError::EOF);
+        Ok(self.buffer.get_i8())
+    }
+    #[inline]
+    fn read_i16(&mut self) -> Result<i16> {
+        Ok(self.read_zigzag()? as i16)
+    }
+    #[inline]
+    fn read_i32(&mut self) -> Result<i32> {
+        Ok(self.read_zigzag()? as i32)
+    }
+    #[inline]
+    fn read_i64(&mut self) -> Result<i64> {
+        self.read_zigzag()
+    }
+    #[inline]
+    fn read_double(&mut self) -> Result<f64> {
+        ensure_err!(self.buffer.remaining() >= 8, ProtocolError::EOF);
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&Vec::<u8>::copy_from_buf(&mut self.buffer, 8));
+        Ok(f64::from_le_bytes(bytes))
+    }
+    #[inline]
+    fn read_float(&mut self) -> Result<f32> {
+        ensure_err!(self.buffer.remaining() >= 4, ProtocolError::EOF);
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(&Vec::<u8>::copy_from_buf(&mut self.buffer, 4));
+        Ok(f32::from_le_bytes(bytes))
+    }
+    #[inline]
+    fn read_string(&mut self) -> Result<String> {
+        let vec = self.read_binary::<Vec<u8>>()?;
+
+        String::from_utf8(vec)
+            .map_err(|utf8_error| anyhow!("deserializing `string` from Thrift compact protocol got invalid utf-8, you need to use `binary` instead: {utf8_error}"))
+    }
+    #[inline]
+    fn read_binary<V: CopyFromBuf>(&mut self) -> Result<V> {
+        let received_len: usize = self
+            .read_varint()?
+            .try_into()
+            .map_err(|_| ProtocolError::InvalidDataLength)?;
+
+        self.charge_bytes(received_len)?;
+        ensure_err!(self.buffer.remaining() >= received_len, ProtocolError::EOF);
+        Ok(V::copy_from_buf(&mut self.buffer, received_len))
+    }
+
+    fn min_size<T: GetTType>() -> usize {
+        match T::TTYPE {
+            TType::Void => 0,
+            TType::Bool => 1,
+            TType::Byte => 1,
+            TType::Double => 8,
+            TType::I16 => 1,
+            TType::I32 => 1,
+            TType::I64 => 1,
+            TType::String => 1,
+            TType::Struct => 1,
+            TType::Map => 1,
+            TType::Set => 1,
+            TType::List => 1,
+            TType::UTF8 => 1,
+            TType::UTF16 => 1,
+            TType::Float => 4,
+            TType::Stop | TType::Stream => unreachable!(),
+        }
+    }
+
+    fn can_advance(&self, bytes: usize) -> bool {
+        self.buffer.can_advance(bytes)
+    }
+
+    #[inline]
+    fn skip(&mut self, field_type: TType) -> Result<()> {
+        thread_local! {
+            static STACK: RefCell<[CompactSkipData; DEFAULT_RECURSION_DEPTH as usize]> =
+                const { RefCell::new([CompactSkipData::Next(TType::Void); DEFAULT_RECURSION_DEPTH as usize]) };
+        }
+        let depth = self.limits.recursion_depth as usize;
+        if depth <= DEFAULT_RECURSION_DEPTH as usize {
+            STACK.with_borrow_mut(|stack| self.skip_fast(field_type, &mut stack[..depth]))
+        } else {
+            let mut stack = vec![CompactSkipData::Next(TType::Void); depth];
+            self.skip_fast(field_type, &mut stack)
+        }
+    }
+}
+
+/// How large an item will be when `serialize()` is called
+#[inline]
+pub fn serialize_size<T>(v: &T) -> usize
+where
+    T: Serialize<CompactProtocolSerializer<SizeCounter>>,
+{
+    let mut sizer = CompactProtocolSerializer::with_buffer(SizeCounter::new());
+    v.rs_thrift_write(&mut sizer);
+    sizer.finish()
+}
+
+/// Serialize a Thrift value using the compact protocol to a pre-allocated buffer.
+/// This will panic if the buffer is not large enough. A buffer at least as
+/// large as the return value of `serialize_size` will not panic.
+#[inline]
+pub fn serialize_to_buffer<T>(v: T, buffer: BytesMut) -> CompactProtocolSerializer<BytesMut>
+where
+    T: Serialize<CompactProtocolSerializer<BytesMut>>,
+{
+    let mut buf = CompactProtocolSerializer::with_buffer(buffer);
+    v.rs_thrift_write(&mut buf);
+    buf
+}
+
+/// Serialize a Thrift value using the compact protocol.
+#[inline]
+pub fn serialize<T>(v: T) -> Bytes
+where
+    T: Serialize<CompactProtocolSerializer<SizeCounter>>
+        + Serialize<CompactProtocolSerializer<BytesMut>>,
+{
+    let sz = serialize_size(&v);
+    let buf = serialize_to_buffer(v, BytesMut::with_capacity(sz));
+    buf.finish()
+}
+
+pub trait DeserializeSlice:
+    for<'a> Deserialize<CompactProtocolDeserializer<Cursor<&'a [u8]>>>
+{
+}
+
+impl<T> DeserializeSlice for T where
+    T: for<'a> Deserialize<CompactProtocolDeserializer<Cursor<&'a [u8]>>>
+{
+}
+
+/// Deserialize a Thrift blob using the compact protocol.
+#[inline]
+pub fn deserialize<T, B, C>(b: B) -> Result<T>
+where
+    B: Into<DeserializeSource<C>>,
+    C: BufExt,
+    T: Deserialize<CompactProtocolDeserializer<C>>,
+{
+    let source: DeserializeSource<C> = b.into();
+    let mut deser = CompactProtocolDeserializer::new(source.0);
+    T::rs_thrift_read(&mut deser)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reader(bytes: &Bytes) -> CompactProtocolDeserializer<Cursor<&[u8]>> {
+        CompactProtocolDeserializer::new(Cursor::new(bytes.as_ref()))
+    }
+
+    #[test]
+    fn zigzag_roundtrip() {
+        for v in [0i64, 1, -1, 63, -64, 12345, -12345, i64::MAX, i64::MIN] {
+            assert_eq!(unzigzag(zigzag(v)), v);
+        }
+    }
+
+    #[test]
+    fn field_id_delta_roundtrip() {
+        let mut ser = CompactProtocolSerializer::with_buffer(BytesMut::new());
+        ser.last_field_id.push(0);
+        ser.write_field_header(COMPACT_I16, 5);
+        let bytes = ser.finish();
+
+        let mut de = reader(&bytes);
+        de.last_field_id.push(0);
+        let (_, ttype, id) = de.read_field_begin(|_| (), &[]).unwrap();
+        assert_eq!(ttype, TType::I16);
+        assert_eq!(id, 5);
+    }
+
+    #[test]
+    fn field_id_delta_wraps_instead_of_panicking() {
+        // A last-id of i16::MAX followed by i16::MIN wraps the delta around to
+        // 1, so the header takes the 4-bit short form; wrapping_sub on the
+        // write side and wrapping_add on the read side must agree and must
+        // not panic at the i16 boundary.
+        let mut ser = CompactProtocolSerializer::with_buffer(BytesMut::new());
+        ser.last_field_id.push(i16::MAX);
+        ser.write_field_header(COMPACT_I16, i16::MIN);
+        let bytes = ser.finish();
+
+        let mut de = reader(&bytes);
+        de.last_field_id.push(i16::MAX);
+        let (_, _, id) = de.read_field_begin(|_| (), &[]).unwrap();
+        assert_eq!(id, i16::MIN);
+    }
+
+    #[test]
+    fn collection_begin_roundtrip_short_and_escaped_size() {
+        // Sizes below 15 pack into the header nibble; 15 and above escape to
+        // a trailing varint, per `write_collection_begin`.
+        for &size in &[0usize, 1, 14, 15, 16, 1000] {
+            let mut ser = CompactProtocolSerializer::with_buffer(BytesMut::new());
+            ser.write_collection_begin(TType::I32, size);
+            let bytes = ser.finish();
+
+            let mut de = reader(&bytes);
+            let (elem_type, got_size) = de.read_collection_begin().unwrap();
+            assert_eq!(elem_type, TType::I32);
+            assert_eq!(got_size, size);
+        }
+    }
+
+    #[test]
+    fn compact_elem_type_rejects_stream() {
+        assert!(compact_elem_type(TType::Stream).is_err());
+        assert!(compact_field_type(TType::Stream).is_err());
+    }
+
+    #[test]
+    fn list_of_i64_charges_real_element_size() {
+        // Mirrors binary_protocol's equivalent test: CompactProtocolDeserializer
+        // ported the same DeserializeLimits budget, so it must charge a
+        // list<i64>'s claimed length at 8 bytes/elem, not a flat 1.
+        let mut ser = CompactProtocolSerializer::with_buffer(BytesMut::new());
+        ser.write_collection_begin(TType::I64, 1000);
+        let bytes = ser.finish();
+
+        let limits = DeserializeLimits {
+            max_total_bytes: Some(1000 * 8 - 1),
+            ..DeserializeLimits::default()
+        };
+        let mut de = CompactProtocolDeserializer::with_limits(Cursor::new(bytes.as_ref()), limits);
+        assert!(de.read_collection_begin().is_err());
+
+        let limits = DeserializeLimits {
+            max_total_bytes: Some(1000 * 8),
+            ..DeserializeLimits::default()
+        };
+        let mut de = CompactProtocolDeserializer::with_limits(Cursor::new(bytes.as_ref()), limits);
+        assert!(de.read_collection_begin().is_ok());
+    }
+
+    #[test]
+    fn container_len_over_max_is_rejected() {
+        let mut ser = CompactProtocolSerializer::with_buffer(BytesMut::new());
+        ser.write_collection_begin(TType::Byte, 10);
+        let bytes = ser.finish();
+
+        let limits = DeserializeLimits {
+            max_container_len: Some(9),
+            ..DeserializeLimits::default()
+        };
+        let mut de = CompactProtocolDeserializer::with_limits(Cursor::new(bytes.as_ref()), limits);
+        assert!(de.read_collection_begin().is_err());
+    }
+}
@@ -0,0 +1,199 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::binary_protocol::BinaryProtocolSerializer;
+use crate::bufext::BufMutExt;
+use crate::serialize::Serialize;
+
+/// A compile-time upper bound on how many bytes `T` can occupy when encoded
+/// with `BinaryProtocol`, borrowed from bzipper's `MAX_SERIALISED_SIZE`.
+///
+/// Only types with a fixed shape -- scalars, and fixed-size arrays of types
+/// that are themselves `MaxSerializedSize` -- can implement this; anything
+/// containing a string, list, map, or other variable-length field has no
+/// such bound and must go through [`crate::binary_protocol::serialize`]
+/// instead. Thrift codegen for a struct built entirely from bounded fields
+/// can derive this by summing each field's bound plus its binary-protocol
+/// field header (1 byte type + 2 byte id) and the trailing field-stop byte.
+pub trait MaxSerializedSize {
+    /// Upper bound, in bytes, on the `BinaryProtocol` encoding of `Self`.
+    const MAX_SERIALIZED_SIZE: usize;
+}
+
+macro_rules! impl_fixed_width {
+    ($($ty:ty => $size:expr),* $(,)?) => {
+        $(
+            impl MaxSerializedSize for $ty {
+                const MAX_SERIALIZED_SIZE: usize = $size;
+            }
+        )*
+    };
+}
+
+impl_fixed_width! {
+    bool => 1,
+    i8 => 1,
+    i16 => 2,
+    i32 => 4,
+    i64 => 8,
+    f32 => 4,
+    f64 => 8,
+}
+
+impl<T: MaxSerializedSize, const N: usize> MaxSerializedSize for [T; N] {
+    // List header (1 byte elem type + 4 byte i32 length) plus N elements.
+    const MAX_SERIALIZED_SIZE: usize = 5 + N * T::MAX_SERIALIZED_SIZE;
+}
+
+/// A `BufMutExt` sink backed by a fixed-size stack array rather than a heap
+/// `BytesMut`. Writes past `N` bytes panic; callers are expected to have
+/// checked `N >= T::MAX_SERIALIZED_SIZE` first, which `serialize_to_array`
+/// does for them.
+pub struct FixedSizeBuf<const N: usize> {
+    data: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> FixedSizeBuf<N> {
+    #[inline]
+    fn new() -> Self {
+        FixedSizeBuf {
+            data: [0u8; N],
+            len: 0,
+        }
+    }
+
+    #[inline]
+    fn push(&mut self, bytes: &[u8]) {
+        let end = self.len + bytes.len();
+        assert!(
+            end <= N,
+            "FixedSizeBuf overflow: MaxSerializedSize bound was smaller than the actual encoding"
+        );
+        self.data[self.len..end].copy_from_slice(bytes);
+        self.len = end;
+    }
+}
+
+impl<const N: usize> BufMutExt for FixedSizeBuf<N> {
+    type Final = ([u8; N], usize);
+
+    #[inline]
+    fn put_u8(&mut self, value: u8) {
+        self.push(&[value])
+    }
+    #[inline]
+    fn put_i8(&mut self, value: i8) {
+        self.push(&value.to_be_bytes())
+    }
+    #[inline]
+    fn put_i16(&mut self, value: i16) {
+        self.push(&value.to_be_bytes())
+    }
+    #[inline]
+    fn put_i32(&mut self, value: i32) {
+        self.push(&value.to_be_bytes())
+    }
+    #[inline]
+    fn put_i64(&mut self, value: i64) {
+        self.push(&value.to_be_bytes())
+    }
+    #[inline]
+    fn put_u32(&mut self, value: u32) {
+        self.push(&value.to_be_bytes())
+    }
+    #[inline]
+    fn put_f32(&mut self, value: f32) {
+        self.push(&value.to_be_bytes())
+    }
+    #[inline]
+    fn put_f64(&mut self, value: f64) {
+        self.push(&value.to_be_bytes())
+    }
+    #[inline]
+    fn put_slice(&mut self, value: &[u8]) {
+        self.push(value)
+    }
+    #[inline]
+    fn finalize(self) -> Self::Final {
+        (self.data, self.len)
+    }
+}
+
+/// Serialize `v` using `BinaryProtocol` directly into a stack `[u8; N]`,
+/// skipping the `SizeCounter` pass that `serialize`/`serialize_to_buffer`
+/// otherwise require before allocating. Requires `N >= T::MAX_SERIALIZED_SIZE`
+/// (checked with a runtime assert, since const generics can't yet compare
+/// against an associated const in a `where` bound); types with variable-length
+/// fields don't implement `MaxSerializedSize` and must use `serialize`.
+///
+/// Returns the buffer along with the number of leading bytes that were
+/// actually written.
+#[inline]
+pub fn serialize_to_array<T, const N: usize>(v: T) -> ([u8; N], usize)
+where
+    T: MaxSerializedSize + Serialize<BinaryProtocolSerializer<FixedSizeBuf<N>>>,
+{
+    assert!(
+        N >= T::MAX_SERIALIZED_SIZE,
+        "serialize_to_array buffer is smaller than T::MAX_SERIALIZED_SIZE"
+    );
+    let mut buf = BinaryProtocolSerializer::with_buffer(FixedSizeBuf::new());
+    v.rs_thrift_write(&mut buf);
+    buf.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_width_bounds_match_binary_protocol_encoding() {
+        assert_eq!(bool::MAX_SERIALIZED_SIZE, 1);
+        assert_eq!(i8::MAX_SERIALIZED_SIZE, 1);
+        assert_eq!(i16::MAX_SERIALIZED_SIZE, 2);
+        assert_eq!(i32::MAX_SERIALIZED_SIZE, 4);
+        assert_eq!(i64::MAX_SERIALIZED_SIZE, 8);
+        assert_eq!(f32::MAX_SERIALIZED_SIZE, 4);
+        assert_eq!(f64::MAX_SERIALIZED_SIZE, 8);
+    }
+
+    #[test]
+    fn array_bound_accounts_for_list_header_and_elements() {
+        assert_eq!(<[i32; 3]>::MAX_SERIALIZED_SIZE, 5 + 3 * 4);
+    }
+
+    #[test]
+    fn fixed_size_buf_accepts_writes_within_capacity() {
+        let mut buf = FixedSizeBuf::<4>::new();
+        buf.put_i32(7);
+        assert_eq!(buf.finalize(), (7i32.to_be_bytes(), 4));
+    }
+
+    #[test]
+    #[should_panic(expected = "FixedSizeBuf overflow")]
+    fn fixed_size_buf_panics_past_capacity() {
+        let mut buf = FixedSizeBuf::<2>::new();
+        buf.put_i32(7);
+    }
+
+    #[test]
+    fn serialize_to_array_writes_fixed_width_value() {
+        let (bytes, len): ([u8; 8], usize) = serialize_to_array(42i32);
+        assert_eq!(len, 4);
+        assert_eq!(&bytes[..len], &42i32.to_be_bytes());
+    }
+}
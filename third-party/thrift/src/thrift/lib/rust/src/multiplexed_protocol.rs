@@ -0,0 +1,389 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use anyhow::Result;
+
+use crate::binary_type::CopyFromBuf;
+use crate::errors::ProtocolError;
+use crate::protocol::Field;
+use crate::protocol::ProtocolReader;
+use crate::protocol::ProtocolWriter;
+use crate::thrift_protocol::MessageType;
+use crate::ttype::TType;
+
+/// Separator between the service name and the method name in a multiplexed
+/// message name, matching Apache Thrift's `TMultiplexedProtocol`.
+pub const MULTIPLEXED_SEPARATOR: &str = ":";
+
+/// Wraps any `ProtocolWriter`/`ProtocolReader` pair so several services can
+/// share one transport. On write, the configured `service_name` is prefixed
+/// onto the outgoing method name (`"ServiceName:method"`); on read, a
+/// prefix is required to match the configured `service_name` (a mismatch is
+/// rejected with `ProtocolError::ServiceNameMismatch`, so a message intended
+/// for a different service sharing the transport can't be silently accepted)
+/// and is then stripped back off so callers see the bare method name.
+///
+/// ```ignore
+/// let inner = BinaryProtocolSerializer::with_buffer(BytesMut::new());
+/// let mut protocol = MultiplexedProtocol::new(inner, "Calculator");
+/// ```
+///
+/// Every other call (struct/field/container reads and writes) is delegated
+/// unchanged to the inner serializer or deserializer, so this composes with
+/// those of `BinaryProtocol`, `CompactProtocol`, or any other `Protocol`
+/// implementation.
+pub struct MultiplexedProtocol<P> {
+    inner: P,
+    service_name: String,
+}
+
+impl<P> MultiplexedProtocol<P> {
+    pub fn new(inner: P, service_name: impl Into<String>) -> Self {
+        Self {
+            inner,
+            service_name: service_name.into(),
+        }
+    }
+
+    pub fn into_inner(self) -> P {
+        self.inner
+    }
+}
+
+impl<P: ProtocolWriter> ProtocolWriter for MultiplexedProtocol<P> {
+    type Final = P::Final;
+
+    #[inline]
+    fn write_message_begin(&mut self, name: &str, type_id: MessageType, seqid: u32) {
+        let prefixed = format!("{}{}{}", self.service_name, MULTIPLEXED_SEPARATOR, name);
+        self.inner.write_message_begin(&prefixed, type_id, seqid);
+    }
+
+    #[inline]
+    fn write_message_end(&mut self) {
+        self.inner.write_message_end()
+    }
+    #[inline]
+    fn write_struct_begin(&mut self, name: &str) {
+        self.inner.write_struct_begin(name)
+    }
+    #[inline]
+    fn write_struct_end(&mut self) {
+        self.inner.write_struct_end()
+    }
+    #[inline]
+    fn write_field_begin(&mut self, name: &str, type_id: TType, id: i16) {
+        self.inner.write_field_begin(name, type_id, id)
+    }
+    #[inline]
+    fn write_field_end(&mut self) {
+        self.inner.write_field_end()
+    }
+    #[inline]
+    fn write_field_stop(&mut self) {
+        self.inner.write_field_stop()
+    }
+    #[inline]
+    fn write_map_begin(&mut self, key_type: TType, value_type: TType, size: usize) {
+        self.inner.write_map_begin(key_type, value_type, size)
+    }
+    #[inline]
+    fn write_map_key_begin(&mut self) {
+        self.inner.write_map_key_begin()
+    }
+    #[inline]
+    fn write_map_value_begin(&mut self) {
+        self.inner.write_map_value_begin()
+    }
+    #[inline]
+    fn write_map_end(&mut self) {
+        self.inner.write_map_end()
+    }
+    #[inline]
+    fn write_list_begin(&mut self, elem_type: TType, size: usize) {
+        self.inner.write_list_begin(elem_type, size)
+    }
+    #[inline]
+    fn write_list_value_begin(&mut self) {
+        self.inner.write_list_value_begin()
+    }
+    #[inline]
+    fn write_list_end(&mut self) {
+        self.inner.write_list_end()
+    }
+    #[inline]
+    fn write_set_begin(&mut self, elem_type: TType, size: usize) {
+        self.inner.write_set_begin(elem_type, size)
+    }
+    #[inline]
+    fn write_set_value_begin(&mut self) {
+        self.inner.write_set_value_begin()
+    }
+    #[inline]
+    fn write_set_end(&mut self) {
+        self.inner.write_set_end()
+    }
+    #[inline]
+    fn write_bool(&mut self, value: bool) {
+        self.inner.write_bool(value)
+    }
+    #[inline]
+    fn write_byte(&mut self, value: i8) {
+        self.inner.write_byte(value)
+    }
+    #[inline]
+    fn write_i16(&mut self, value: i16) {
+        self.inner.write_i16(value)
+    }
+    #[inline]
+    fn write_i32(&mut self, value: i32) {
+        self.inner.write_i32(value)
+    }
+    #[inline]
+    fn write_i64(&mut self, value: i64) {
+        self.inner.write_i64(value)
+    }
+    #[inline]
+    fn write_double(&mut self, value: f64) {
+        self.inner.write_double(value)
+    }
+    #[inline]
+    fn write_float(&mut self, value: f32) {
+        self.inner.write_float(value)
+    }
+    #[inline]
+    fn write_string(&mut self, value: &str) {
+        self.inner.write_string(value)
+    }
+    #[inline]
+    fn write_binary(&mut self, value: &[u8]) {
+        self.inner.write_binary(value)
+    }
+    #[inline]
+    fn finish(self) -> P::Final {
+        self.inner.finish()
+    }
+}
+
+impl<P: ProtocolReader> ProtocolReader for MultiplexedProtocol<P> {
+    #[inline]
+    fn read_message_begin<F, T>(&mut self, msgfn: F) -> Result<(T, MessageType, u32)>
+    where
+        F: FnOnce(&[u8]) -> T,
+    {
+        let service_name = self.service_name.as_bytes();
+        let mut service_mismatch = false;
+        let result = self.inner.read_message_begin(|full_name| {
+            let method = match full_name
+                .iter()
+                .position(|&b| b == MULTIPLEXED_SEPARATOR.as_bytes()[0])
+            {
+                Some(idx) => {
+                    if &full_name[..idx] != service_name {
+                        service_mismatch = true;
+                    }
+                    &full_name[idx + 1..]
+                }
+                None => {
+                    // No separator at all means the wire name carries no
+                    // service qualifier, which is just as much a routing
+                    // mismatch as a wrong one: without it we can't tell this
+                    // message was actually meant for `self.service_name`.
+                    service_mismatch = true;
+                    full_name
+                }
+            };
+            msgfn(method)
+        });
+        if service_mismatch {
+            bail_err!(ProtocolError::ServiceNameMismatch);
+        }
+        result
+    }
+    #[inline]
+    fn read_message_end(&mut self) -> Result<()> {
+        self.inner.read_message_end()
+    }
+    #[inline]
+    fn read_struct_begin<F, T>(&mut self, namefn: F) -> Result<T>
+    where
+        F: FnOnce(&[u8]) -> T,
+    {
+        self.inner.read_struct_begin(namefn)
+    }
+    #[inline]
+    fn read_struct_end(&mut self) -> Result<()> {
+        self.inner.read_struct_end()
+    }
+    #[inline]
+    fn read_field_begin<F, T>(&mut self, fieldfn: F, fields: &[Field]) -> Result<(T, TType, i16)>
+    where
+        F: FnOnce(&[u8]) -> T,
+    {
+        self.inner.read_field_begin(fieldfn, fields)
+    }
+    #[inline]
+    fn read_field_end(&mut self) -> Result<()> {
+        self.inner.read_field_end()
+    }
+    #[inline]
+    fn read_map_begin_unchecked(&mut self) -> Result<(TType, TType, Option<usize>)> {
+        self.inner.read_map_begin_unchecked()
+    }
+    #[inline]
+    fn read_map_key_begin(&mut self) -> Result<bool> {
+        self.inner.read_map_key_begin()
+    }
+    #[inline]
+    fn read_map_value_begin(&mut self) -> Result<()> {
+        self.inner.read_map_value_begin()
+    }
+    #[inline]
+    fn read_map_value_end(&mut self) -> Result<()> {
+        self.inner.read_map_value_end()
+    }
+    #[inline]
+    fn read_map_end(&mut self) -> Result<()> {
+        self.inner.read_map_end()
+    }
+    #[inline]
+    fn read_list_begin_unchecked(&mut self) -> Result<(TType, Option<usize>)> {
+        self.inner.read_list_begin_unchecked()
+    }
+    #[inline]
+    fn read_list_value_begin(&mut self) -> Result<bool> {
+        self.inner.read_list_value_begin()
+    }
+    #[inline]
+    fn read_list_value_end(&mut self) -> Result<()> {
+        self.inner.read_list_value_end()
+    }
+    #[inline]
+    fn read_list_end(&mut self) -> Result<()> {
+        self.inner.read_list_end()
+    }
+    #[inline]
+    fn read_set_begin_unchecked(&mut self) -> Result<(TType, Option<usize>)> {
+        self.inner.read_set_begin_unchecked()
+    }
+    #[inline]
+    fn read_set_value_begin(&mut self) -> Result<bool> {
+        self.inner.read_set_value_begin()
+    }
+    #[inline]
+    fn read_set_value_end(&mut self) -> Result<()> {
+        self.inner.read_set_value_end()
+    }
+    #[inline]
+    fn read_set_end(&mut self) -> Result<()> {
+        self.inner.read_set_end()
+    }
+    #[inline]
+    fn read_bool(&mut self) -> Result<bool> {
+        self.inner.read_bool()
+    }
+    #[inline]
+    fn read_byte(&mut self) -> Result<i8> {
+        self.inner.read_byte()
+    }
+    #[inline]
+    fn read_i16(&mut self) -> Result<i16> {
+        self.inner.read_i16()
+    }
+    #[inline]
+    fn read_i32(&mut self) -> Result<i32> {
+        self.inner.read_i32()
+    }
+    #[inline]
+    fn read_i64(&mut self) -> Result<i64> {
+        self.inner.read_i64()
+    }
+    #[inline]
+    fn read_double(&mut self) -> Result<f64> {
+        self.inner.read_double()
+    }
+    #[inline]
+    fn read_float(&mut self) -> Result<f32> {
+        self.inner.read_float()
+    }
+    #[inline]
+    fn read_string(&mut self) -> Result<String> {
+        self.inner.read_string()
+    }
+    #[inline]
+    fn read_binary<V: CopyFromBuf>(&mut self) -> Result<V> {
+        self.inner.read_binary()
+    }
+
+    fn min_size<T: crate::ttype::GetTType>() -> usize {
+        P::min_size::<T>()
+    }
+
+    fn can_advance(&self, bytes: usize) -> bool {
+        self.inner.can_advance(bytes)
+    }
+
+    #[inline]
+    fn skip(&mut self, field_type: TType) -> Result<()> {
+        self.inner.skip(field_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use bytes::BytesMut;
+
+    use super::*;
+    use crate::binary_protocol::BinaryProtocolDeserializer;
+    use crate::binary_protocol::BinaryProtocolSerializer;
+
+    fn wire_message(name: &str) -> bytes::Bytes {
+        let mut ser = BinaryProtocolSerializer::with_buffer(BytesMut::new());
+        ser.write_message_begin(name, MessageType::Call, 1);
+        ser.finish()
+    }
+
+    fn read_method(wire: &bytes::Bytes, service_name: &str) -> Result<String> {
+        let inner = BinaryProtocolDeserializer::new(Cursor::new(wire.as_ref()));
+        let mut protocol = MultiplexedProtocol::new(inner, service_name);
+        let (method, _, _) = protocol.read_message_begin(|bytes| {
+            String::from_utf8(bytes.to_vec()).unwrap()
+        })?;
+        Ok(method)
+    }
+
+    #[test]
+    fn accepts_and_strips_matching_service_prefix() {
+        let wire = wire_message("Calculator:add");
+        assert_eq!(read_method(&wire, "Calculator").unwrap(), "add");
+    }
+
+    #[test]
+    fn rejects_mismatched_service_prefix() {
+        let wire = wire_message("Calculator:add");
+        assert!(read_method(&wire, "Other").is_err());
+    }
+
+    #[test]
+    fn rejects_missing_service_prefix() {
+        // No ':' at all means the wire name carries no service qualifier,
+        // which must be rejected rather than silently accepted as the method.
+        let wire = wire_message("add");
+        assert!(read_method(&wire, "Calculator").is_err());
+    }
+}